@@ -0,0 +1,41 @@
+/// Marker trait for guard types that release a held lock on an aggregate when dropped.
+///
+/// This is a pessimistic-locking escape hatch for stores that support row-level locking, as an
+/// alternative to the expected-version check built into
+/// [`serialize_events`](super::serialized_event::serialize_events), which needs no held lock at
+/// all. Stores that want row locking implement this for their own guard type instead of using
+/// [`AggregateLockGuard`] directly.
+pub trait UnlockOnDrop: Drop {}
+
+/// An RAII guard representing an exclusive lock on a single aggregate instance, held by a
+/// repository for the duration of a read-modify-write cycle. The lock is released when the guard
+/// is dropped.
+pub struct AggregateLockGuard {
+    aggregate_id: String,
+    unlock: Option<Box<dyn FnOnce(&str) + Send>>,
+}
+
+impl AggregateLockGuard {
+    /// Create a new guard for `aggregate_id` that calls `unlock` when dropped.
+    pub fn new(aggregate_id: String, unlock: Box<dyn FnOnce(&str) + Send>) -> Self {
+        Self {
+            aggregate_id,
+            unlock: Some(unlock),
+        }
+    }
+
+    /// The aggregate instance this guard holds a lock on.
+    pub fn aggregate_id(&self) -> &str {
+        &self.aggregate_id
+    }
+}
+
+impl Drop for AggregateLockGuard {
+    fn drop(&mut self) {
+        if let Some(unlock) = self.unlock.take() {
+            unlock(&self.aggregate_id);
+        }
+    }
+}
+
+impl UnlockOnDrop for AggregateLockGuard {}
@@ -0,0 +1,82 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+/// Errors that can occur while serializing, persisting, or loading aggregates through the
+/// `persist` module.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PersistenceError {
+    /// Serializing or deserializing a stored payload, metadata, or aggregate value as JSON (via
+    /// `serde_json`) failed. Errors from the other formats are reported as
+    /// [`PersistenceError::UnknownError`].
+    Serialization(serde_json::Error),
+    /// A [`SerializedEvent`](super::SerializedEvent) chain failed hash-chain verification: the
+    /// event at `sequence` for `aggregate_id` either has a corrupted `content_hash` or does not
+    /// chain from its predecessor's `content_hash`. See `verify_chain`.
+    IntegrityViolation {
+        /// The aggregate instance whose event chain failed verification.
+        aggregate_id: String,
+        /// The sequence number of the event that failed verification.
+        sequence: usize,
+    },
+    /// A writer's batch of events did not contiguously extend the sequence it expected to,
+    /// meaning another writer already committed events to the same aggregate.
+    OptimisticConcurrency {
+        /// The sequence number the writer expected its next event to have.
+        expected: usize,
+        /// The sequence number the event actually had.
+        actual: usize,
+    },
+    /// An aggregate's `current_sequence` is already `usize::MAX`, so the next expected sequence
+    /// can't be computed. Distinct from [`PersistenceError::OptimisticConcurrency`], which always
+    /// reflects two different sequence numbers disagreeing, not an overflowed computation.
+    SequenceOverflow {
+        /// The aggregate instance whose sequence has reached `usize::MAX`.
+        aggregate_id: String,
+    },
+    /// An error from an underlying serialization format or other dependency that doesn't warrant
+    /// its own variant.
+    UnknownError(Box<dyn StdError + Send + Sync>),
+}
+
+impl fmt::Display for PersistenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Serialization(error) => write!(f, "serialization error: {error}"),
+            Self::IntegrityViolation {
+                aggregate_id,
+                sequence,
+            } => write!(
+                f,
+                "event chain integrity violation for aggregate '{aggregate_id}' at sequence {sequence}"
+            ),
+            Self::OptimisticConcurrency { expected, actual } => write!(
+                f,
+                "optimistic concurrency conflict: expected sequence {expected}, found {actual}"
+            ),
+            Self::SequenceOverflow { aggregate_id } => write!(
+                f,
+                "sequence overflow for aggregate '{aggregate_id}': current_sequence is already usize::MAX"
+            ),
+            Self::UnknownError(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl StdError for PersistenceError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Serialization(error) => Some(error),
+            Self::UnknownError(error) => Some(error.as_ref()),
+            Self::IntegrityViolation { .. }
+            | Self::OptimisticConcurrency { .. }
+            | Self::SequenceOverflow { .. } => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for PersistenceError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Serialization(error)
+    }
+}
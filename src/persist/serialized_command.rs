@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::persist::serialized_event::Timestamp;
+use crate::persist::PersistenceError;
+
+/// A persisted record of the command that produced a batch of events, mirroring
+/// [`SerializedEvent`](super::SerializedEvent) but for the command rather than its resulting
+/// events. Storing commands is opt-in; repositories that don't call
+/// [`serialize_command`]/[`deserialize_command`] are unaffected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerializedCommand {
+    /// The id of the aggregate instance the command was applied to.
+    pub aggregate_id: String,
+    /// The type of aggregate the command was applied to.
+    pub aggregate_type: String,
+    /// The type of command that was executed.
+    pub command_type: String,
+    /// The contiguous range of event sequence numbers emitted by this command.
+    pub sequence_range: Range<usize>,
+    /// The serialized command payload.
+    pub payload: Value,
+    /// Additional metadata, serialized from a `HashMap<String,String>`.
+    pub metadata: Value,
+    /// The time the command was executed.
+    pub timestamp: Timestamp,
+}
+
+impl SerializedCommand {
+    /// Create a new [`SerializedCommand`] with the given values.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        aggregate_id: String,
+        aggregate_type: String,
+        command_type: String,
+        sequence_range: Range<usize>,
+        payload: Value,
+        metadata: Value,
+        timestamp: Timestamp,
+    ) -> Self {
+        Self {
+            aggregate_id,
+            aggregate_type,
+            command_type,
+            sequence_range,
+            payload,
+            metadata,
+            timestamp,
+        }
+    }
+}
+
+/// Serialize a command, together with the contiguous range of event sequence numbers it produced,
+/// into a [`SerializedCommand`] ready for a repository to persist. Unlike
+/// [`serialize_events`](super::serialize_events), no generic repository code calls this for you —
+/// repositories that want a command-history audit trail call it directly from their commit path.
+#[allow(clippy::too_many_arguments)]
+pub fn serialize_command<C: Serialize>(
+    aggregate_id: String,
+    aggregate_type: String,
+    command_type: String,
+    sequence_range: Range<usize>,
+    command: &C,
+    metadata: &HashMap<String, String>,
+    timestamp: Timestamp,
+) -> Result<SerializedCommand, PersistenceError> {
+    let payload = serde_json::to_value(command)?;
+    let metadata = serde_json::to_value(metadata)?;
+    Ok(SerializedCommand::new(
+        aggregate_id,
+        aggregate_type,
+        command_type,
+        sequence_range,
+        payload,
+        metadata,
+        timestamp,
+    ))
+}
+
+/// Deserialize a [`SerializedCommand`]'s payload and metadata back into their original types.
+///
+/// Called by repository implementations that load command history back out of their store, the
+/// mirror image of [`serialize_command`].
+pub fn deserialize_command<C: DeserializeOwned>(
+    command: SerializedCommand,
+) -> Result<(C, HashMap<String, String>), PersistenceError> {
+    let payload = serde_json::from_value(command.payload)?;
+    let metadata = serde_json::from_value(command.metadata)?;
+    Ok((payload, metadata))
+}
+
+/// A query for looking up persisted [`SerializedCommand`]s, i.e. the audit trail of what commands
+/// produced a given aggregate's events.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandHistoryCriteria<'a> {
+    /// All commands applied to a single aggregate instance, in `sequence_range` order.
+    AggregateId(&'a str),
+    /// All commands of a given type, across aggregates.
+    CommandType(&'a str),
+    /// All commands committed between `start` and `end`.
+    TimeRange {
+        /// The start (inclusive) of the time window.
+        start: Timestamp,
+        /// The end (exclusive) of the time window.
+        end: Timestamp,
+    },
+    /// All commands for `aggregate_id` whose `sequence_range` intersects `range`.
+    SequenceRange {
+        /// The aggregate instance to search.
+        aggregate_id: &'a str,
+        /// The sequence window to intersect against each command's `sequence_range`.
+        range: Range<usize>,
+    },
+}
@@ -1,10 +1,37 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
+use sha2::{Digest, Sha256};
+
+use crate::persist::serde_format::SerdeFormat;
 use crate::{Aggregate, DomainEvent, EventEnvelope};
 use serde_json::Value;
 
 use crate::persist::{EventStoreAggregateContext, EventUpcaster, PersistenceError};
 
+/// A hex-encoded SHA-256 digest, used to link [`SerializedEvent`]s for the same aggregate into a
+/// tamper-evident chain.
+pub type ContentHash = String;
+
+/// A committed-at time, behind the optional `chrono` feature.
+#[cfg(feature = "chrono")]
+pub type Timestamp = chrono::DateTime<chrono::Utc>;
+
+/// Stand-in for [`Timestamp`] when the `chrono` feature is disabled.
+#[cfg(not(feature = "chrono"))]
+pub type Timestamp = ();
+
+/// The current time as a [`Timestamp`].
+fn now_timestamp() -> Timestamp {
+    #[cfg(feature = "chrono")]
+    {
+        chrono::Utc::now()
+    }
+    #[cfg(not(feature = "chrono"))]
+    {
+    }
+}
+
 /// A serialized version of an event with metadata.
 /// Used by repositories to store and load events from a database.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -19,23 +46,46 @@ pub struct SerializedEvent {
     pub event_type: String,
     /// The version of event that is serialized.
     pub event_version: String,
-    /// The serialized domain event.
-    pub payload: Value,
-    /// Additional metadata, serialized from a HashMap<String,String>.
-    pub metadata: Value,
+    /// The serialized domain event, encoded using `format`.
+    pub payload: Vec<u8>,
+    /// Additional metadata, serialized from a `HashMap<String,String>` using `format`.
+    pub metadata: Vec<u8>,
+    /// The format `payload` and `metadata` are encoded in, so a store can round-trip any of the
+    /// formats supported by [`SerdeFormat`] without knowing in advance which one was used.
+    pub format: SerdeFormat,
+    /// The SHA-256 digest of this event's `aggregate_id`, `sequence`, `payload`, `metadata`,
+    /// `prev_hash` and `timestamp`, computed at serialization time.
+    pub content_hash: ContentHash,
+    /// The [`content_hash`](Self::content_hash) of the preceding event for this aggregate, or
+    /// `None` for an aggregate's first event. See [`verify_chain`].
+    pub prev_hash: Option<ContentHash>,
+    /// The time this event was committed. Defaults to [`now_timestamp`] at serialization time.
+    pub timestamp: Timestamp,
 }
 
 impl SerializedEvent {
     /// Create a new [`SerializedEvent`] with the given values.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aggregate_id: String,
         sequence: usize,
         aggregate_type: String,
         event_type: String,
         event_version: String,
-        payload: Value,
-        metadata: Value,
+        payload: Vec<u8>,
+        metadata: Vec<u8>,
+        format: SerdeFormat,
+        prev_hash: Option<ContentHash>,
+        timestamp: Timestamp,
     ) -> Self {
+        let content_hash = content_hash(
+            &aggregate_id,
+            sequence,
+            &payload,
+            &metadata,
+            prev_hash.as_ref(),
+            &timestamp,
+        );
         Self {
             aggregate_id,
             sequence,
@@ -44,9 +94,66 @@ impl SerializedEvent {
             event_version,
             payload,
             metadata,
+            format,
+            content_hash,
+            prev_hash,
+            timestamp,
         }
     }
 
+    /// Serialize an [`EventEnvelope`] using the given `format`, chaining it from `prev_hash` (the
+    /// [`content_hash`](Self::content_hash) of the previous event for this aggregate, or `None`
+    /// if this is the aggregate's first event). `timestamp` defaults to [`now_timestamp`] when
+    /// `None`. Callers serializing a batch together should use [`serialize_events`] instead.
+    pub fn serialize<A: Aggregate>(
+        event: &EventEnvelope<A>,
+        format: SerdeFormat,
+        prev_hash: Option<ContentHash>,
+        timestamp: Option<Timestamp>,
+    ) -> Result<Self, PersistenceError> {
+        let aggregate_type = A::aggregate_type();
+        let event_type = event.payload.event_type();
+        let event_version = event.payload.event_version();
+        let payload = format.serialize(&event.payload)?;
+        let metadata = format.serialize(&event.metadata)?;
+        let timestamp = timestamp.unwrap_or_else(now_timestamp);
+        let content_hash = content_hash(
+            &event.aggregate_id,
+            event.sequence,
+            &payload,
+            &metadata,
+            prev_hash.as_ref(),
+            &timestamp,
+        );
+        Ok(Self {
+            aggregate_id: event.aggregate_id.clone(),
+            sequence: event.sequence,
+            aggregate_type,
+            event_type,
+            event_version,
+            payload,
+            metadata,
+            format,
+            content_hash,
+            prev_hash,
+            timestamp,
+        })
+    }
+
+    /// Deserialize this event's `payload` and `metadata` back into an [`EventEnvelope`], using
+    /// `self.format` to decode the stored bytes.
+    pub fn deserialize<A: Aggregate>(self) -> Result<EventEnvelope<A>, PersistenceError> {
+        let payload = self.format.deserialize(&self.payload)?;
+        let metadata = self.format.deserialize(&self.metadata)?;
+        Ok(EventEnvelope {
+            aggregate_id: self.aggregate_id,
+            sequence: self.sequence,
+            payload,
+            metadata,
+            timestamp: self.timestamp,
+        })
+    }
+
     pub(crate) fn upcast(self, upcasters: &[Box<dyn EventUpcaster>]) -> Self {
         upcasters.iter().fold(self, |event, upcaster| {
             if upcaster.can_upcast(&event.event_type, &event.event_version) {
@@ -56,25 +163,161 @@ impl SerializedEvent {
             }
         })
     }
+
+    /// Decode `payload` as a [`serde_json::Value`], regardless of the event's configured wire
+    /// `format`. Used by `EventUpcaster`s that inspect or rewrite the payload; pair with
+    /// [`set_payload_value`](Self::set_payload_value) to write it back.
+    ///
+    /// Only self-describing formats (`Json`, `MessagePack`) support this; `Postcard` and
+    /// `Bincode` return a deserialization error.
+    pub fn payload_value(&self) -> Result<Value, PersistenceError> {
+        self.format.deserialize(&self.payload)
+    }
+
+    /// Re-encode `payload` from a [`serde_json::Value`] using the event's existing `format`, and
+    /// refresh `content_hash` so the hash chain stays consistent after an upcaster rewrites the
+    /// payload. See [`payload_value`](Self::payload_value).
+    pub fn set_payload_value(&mut self, value: &Value) -> Result<(), PersistenceError> {
+        self.payload = self.format.serialize(value)?;
+        self.content_hash = content_hash(
+            &self.aggregate_id,
+            self.sequence,
+            &self.payload,
+            &self.metadata,
+            self.prev_hash.as_ref(),
+            &self.timestamp,
+        );
+        Ok(())
+    }
 }
 
+/// Serialize a batch of events about to be committed, checking that `events` contiguously extends
+/// `current_sequence` (the [`SerializedSnapshot::current_sequence`] read before this
+/// read-modify-write cycle began, or `0` for a new aggregate).
+///
+/// This only checks the in-memory batch against the sequence this writer read; catching an actual
+/// concurrent writer needs the backing store to also enforce `(aggregate_id, sequence)`
+/// uniqueness. Stores that hold a lock across the whole cycle instead can use
+/// [`AggregateLockGuard`](super::AggregateLockGuard).
 pub(crate) fn serialize_events<A: Aggregate>(
     events: &[EventEnvelope<A>],
+    format: SerdeFormat,
+    timestamp: Option<Timestamp>,
+    current_sequence: usize,
 ) -> Result<Vec<SerializedEvent>, PersistenceError> {
-    let mut result = Vec::default();
+    if events.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut result = Vec::with_capacity(events.len());
+    let mut prev_hash = None;
+    let mut expected_sequence =
+        current_sequence
+            .checked_add(1)
+            .ok_or_else(|| PersistenceError::SequenceOverflow {
+                aggregate_id: events
+                    .first()
+                    .map_or_else(String::new, |event| event.aggregate_id.clone()),
+            })?;
     for event in events {
-        result.push(SerializedEvent::try_from(event)?);
+        if event.sequence != expected_sequence {
+            return Err(PersistenceError::OptimisticConcurrency {
+                expected: expected_sequence,
+                actual: event.sequence,
+            });
+        }
+        let serialized = SerializedEvent::serialize(event, format, prev_hash, timestamp)?;
+        prev_hash = Some(serialized.content_hash.clone());
+        expected_sequence += 1;
+        result.push(serialized);
     }
     Ok(result)
 }
 
+/// Walk a batch of [`SerializedEvent`]s as returned from a store, recomputing each
+/// [`content_hash`](SerializedEvent::content_hash) and checking it against the chain to detect
+/// out-of-band tampering.
+///
+/// The chain is per-aggregate; `events` is sorted by `sequence` within each aggregate before
+/// checking, so a store may return events interleaved or out of order. The lowest-sequence event
+/// for each aggregate is trusted as that chain's root, since `events` may be a partial slice.
+///
+/// Must run before `upcast`, since upcasting rewrites `payload` and invalidates its hash.
+pub(crate) fn verify_chain(events: &[SerializedEvent]) -> Result<(), PersistenceError> {
+    for event in events {
+        let recomputed = content_hash(
+            &event.aggregate_id,
+            event.sequence,
+            &event.payload,
+            &event.metadata,
+            event.prev_hash.as_ref(),
+            &event.timestamp,
+        );
+        if event.content_hash != recomputed {
+            return Err(PersistenceError::IntegrityViolation {
+                aggregate_id: event.aggregate_id.clone(),
+                sequence: event.sequence,
+            });
+        }
+    }
+
+    let mut by_aggregate: HashMap<&str, Vec<&SerializedEvent>> = HashMap::new();
+    for event in events {
+        by_aggregate
+            .entry(event.aggregate_id.as_str())
+            .or_default()
+            .push(event);
+    }
+    for aggregate_events in by_aggregate.values_mut() {
+        aggregate_events.sort_by_key(|event| event.sequence);
+        let mut expected_prev_hash: Option<&ContentHash> = None;
+        for (index, event) in aggregate_events.iter().enumerate() {
+            if index > 0 && event.prev_hash.as_ref() != expected_prev_hash {
+                return Err(PersistenceError::IntegrityViolation {
+                    aggregate_id: event.aggregate_id.clone(),
+                    sequence: event.sequence,
+                });
+            }
+            expected_prev_hash = Some(&event.content_hash);
+        }
+    }
+    Ok(())
+}
+
+fn content_hash(
+    aggregate_id: &str,
+    sequence: usize,
+    payload: &[u8],
+    metadata: &[u8],
+    prev_hash: Option<&ContentHash>,
+    timestamp: &Timestamp,
+) -> ContentHash {
+    let mut hasher = Sha256::new();
+    hasher.update(aggregate_id.as_bytes());
+    hasher.update(sequence.to_be_bytes());
+    hasher.update(u64::try_from(payload.len()).unwrap_or(u64::MAX).to_be_bytes());
+    hasher.update(payload);
+    hasher.update(
+        u64::try_from(metadata.len())
+            .unwrap_or(u64::MAX)
+            .to_be_bytes(),
+    );
+    hasher.update(metadata);
+    hasher.update(prev_hash.map_or("", |hash| hash.as_str()).as_bytes());
+    hasher.update(format!("{timestamp:?}").as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
 pub(crate) fn deserialize_events<A: Aggregate>(
     events: Vec<SerializedEvent>,
     upcasters: &[Box<dyn EventUpcaster>],
+    verify_integrity: bool,
 ) -> Result<Vec<EventEnvelope<A>>, PersistenceError> {
+    if verify_integrity {
+        verify_chain(&events)?;
+    }
     let mut results = Vec::default();
     for event in events.into_iter().map(|event| event.upcast(upcasters)) {
-        results.push(EventEnvelope::<A>::try_from(event)?);
+        results.push(event.deserialize()?);
     }
     Ok(results)
 }
@@ -83,20 +326,7 @@ impl<A: Aggregate> TryFrom<&EventEnvelope<A>> for SerializedEvent {
     type Error = PersistenceError;
 
     fn try_from(event: &EventEnvelope<A>) -> Result<Self, Self::Error> {
-        let aggregate_type = A::aggregate_type();
-        let event_type = event.payload.event_type();
-        let event_version = event.payload.event_version();
-        let payload = serde_json::to_value(&event.payload)?;
-        let metadata = serde_json::to_value(&event.metadata)?;
-        Ok(Self {
-            aggregate_id: event.aggregate_id.clone(),
-            sequence: event.sequence,
-            aggregate_type,
-            event_type,
-            event_version,
-            payload,
-            metadata,
-        })
+        Self::serialize(event, SerdeFormat::default(), None, None)
     }
 }
 
@@ -112,6 +342,8 @@ pub struct SerializedSnapshot {
     pub current_sequence: usize,
     /// The last committed snapshot version for this aggregate instance.
     pub current_snapshot: usize,
+    /// The time the last event folded into this snapshot was committed.
+    pub last_update: Timestamp,
 }
 
 impl<A: Aggregate> TryFrom<SerializedSnapshot> for EventStoreAggregateContext<A> {
@@ -124,6 +356,7 @@ impl<A: Aggregate> TryFrom<SerializedSnapshot> for EventStoreAggregateContext<A>
             aggregate,
             current_sequence: snapshot.current_sequence,
             current_snapshot: Some(snapshot.current_snapshot),
+            last_update: snapshot.last_update,
         })
     }
 }
@@ -132,13 +365,173 @@ impl<A: Aggregate> TryFrom<SerializedEvent> for EventEnvelope<A> {
     type Error = PersistenceError;
 
     fn try_from(event: SerializedEvent) -> Result<Self, Self::Error> {
-        let payload = serde_json::from_value(event.payload)?;
-        let metadata = serde_json::from_value(event.metadata)?;
-        Ok(Self {
-            aggregate_id: event.aggregate_id,
-            sequence: event.sequence,
-            payload,
-            metadata,
-        })
+        event.deserialize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(aggregate_id: &str, sequence: usize, prev_hash: Option<ContentHash>) -> SerializedEvent {
+        SerializedEvent::new(
+            aggregate_id.to_string(),
+            sequence,
+            "TestAggregate".to_string(),
+            "TestEvent".to_string(),
+            "1.0".to_string(),
+            b"payload".to_vec(),
+            b"{}".to_vec(),
+            SerdeFormat::Json,
+            prev_hash,
+            now_timestamp(),
+        )
+    }
+
+    #[test]
+    fn verify_chain_accepts_a_well_formed_chain() {
+        let first = event("agg-1", 1, None);
+        let second = event("agg-1", 2, Some(first.content_hash.clone()));
+        assert!(verify_chain(&[first, second]).is_ok());
+    }
+
+    #[test]
+    fn verify_chain_rejects_a_tampered_payload() {
+        let first = event("agg-1", 1, None);
+        let mut second = event("agg-1", 2, Some(first.content_hash.clone()));
+        second.payload = b"tampered".to_vec();
+        assert!(matches!(
+            verify_chain(&[first, second]),
+            Err(PersistenceError::IntegrityViolation { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_chain_rejects_a_deleted_and_relinked_event() {
+        let first = event("agg-1", 1, None);
+        let second = event("agg-1", 2, Some(first.content_hash.clone()));
+        let mut third = event("agg-1", 3, Some(second.content_hash.clone()));
+        // Drop `second` and relink `third` to `first`, as if `second` had been deleted out of band.
+        third.prev_hash = Some(first.content_hash.clone());
+        assert!(matches!(
+            verify_chain(&[first, third]),
+            Err(PersistenceError::IntegrityViolation { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_chain_accepts_interleaved_aggregates() {
+        let a1 = event("agg-a", 1, None);
+        let b1 = event("agg-b", 1, None);
+        let a2 = event("agg-a", 2, Some(a1.content_hash.clone()));
+        let b2 = event("agg-b", 2, Some(b1.content_hash.clone()));
+        assert!(verify_chain(&[a1, b1, a2, b2]).is_ok());
+    }
+
+    #[test]
+    fn verify_chain_accepts_a_single_aggregate_out_of_slice_order() {
+        let first = event("agg-1", 1, None);
+        let second = event("agg-1", 2, Some(first.content_hash.clone()));
+        let third = event("agg-1", 3, Some(second.content_hash.clone()));
+        assert!(verify_chain(&[first, third, second]).is_ok());
+    }
+
+    #[test]
+    fn verify_chain_trusts_the_first_event_of_a_partial_slice_as_the_root() {
+        // `second` isn't a chain root (`prev_hash` is `Some`), but it's the first event seen for
+        // `agg-1` in this slice, so it's trusted rather than rejected for having a predecessor.
+        let first = event("agg-1", 1, None);
+        let second = event("agg-1", 2, Some(first.content_hash.clone()));
+        assert!(verify_chain(&[second]).is_ok());
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct TestAggregate;
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct TestEvent {
+        value: u32,
+    }
+
+    impl DomainEvent for TestEvent {
+        fn event_type(&self) -> String {
+            "TestEvent".to_string()
+        }
+
+        fn event_version(&self) -> String {
+            "1.0".to_string()
+        }
+    }
+
+    impl Aggregate for TestAggregate {
+        type Command = ();
+        type Event = TestEvent;
+        type Error = std::convert::Infallible;
+        type Services = ();
+
+        fn aggregate_type() -> String {
+            "TestAggregate".to_string()
+        }
+
+        async fn handle(
+            &self,
+            _command: Self::Command,
+            _services: &Self::Services,
+        ) -> Result<Vec<Self::Event>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn apply(&mut self, _event: Self::Event) {}
+    }
+
+    fn envelope(aggregate_id: &str, sequence: usize, value: u32) -> EventEnvelope<TestAggregate> {
+        EventEnvelope {
+            aggregate_id: aggregate_id.to_string(),
+            sequence,
+            payload: TestEvent { value },
+            metadata: HashMap::new(),
+            timestamp: now_timestamp(),
+        }
+    }
+
+    #[test]
+    fn serialize_events_returns_empty_for_an_empty_batch() {
+        let events: Vec<EventEnvelope<TestAggregate>> = Vec::new();
+        let result = serialize_events(&events, SerdeFormat::Json, None, usize::MAX);
+        assert_eq!(result.unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn serialize_events_chains_a_contiguous_batch() {
+        let events = vec![envelope("agg-1", 1, 1), envelope("agg-1", 2, 2)];
+        let serialized = serialize_events(&events, SerdeFormat::Json, None, 0).unwrap();
+        assert_eq!(serialized[0].prev_hash, None);
+        assert_eq!(
+            serialized[1].prev_hash,
+            Some(serialized[0].content_hash.clone())
+        );
+    }
+
+    #[test]
+    fn serialize_events_rejects_a_gap_in_sequence() {
+        let events = vec![envelope("agg-1", 3, 1)];
+        let err = serialize_events(&events, SerdeFormat::Json, None, 0).unwrap_err();
+        assert!(matches!(
+            err,
+            PersistenceError::OptimisticConcurrency {
+                expected: 1,
+                actual: 3
+            }
+        ));
+    }
+
+    #[test]
+    fn serialize_events_reports_sequence_overflow_distinctly_from_optimistic_concurrency() {
+        let events = vec![envelope("agg-1", 1, 1)];
+        let err = serialize_events(&events, SerdeFormat::Json, None, usize::MAX).unwrap_err();
+        assert!(matches!(
+            err,
+            PersistenceError::SequenceOverflow { aggregate_id } if aggregate_id == "agg-1"
+        ));
     }
 }